@@ -0,0 +1,381 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, Weak};
+
+use futures::future::{BoxFuture, FutureExt, Shared};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::{self, Duration};
+use tokio_rustls::rustls::ServerName;
+use tokio_rustls::TlsConnector;
+
+use crate::tls::{self, TlsConfig};
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FirewallRule {
+    pub app_name: String,
+    /// Domains allowed for this app. A leading `*.` matches any subdomain (e.g.
+    /// `*.example.com` matches `api.example.com` but not `example.com` itself or
+    /// `evil-example.com`); anything else must match the queried domain exactly.
+    pub allowed_domains: Vec<String>,
+    /// IP ranges allowed for this app, e.g. `"10.0.0.0/8"`. A bare IP with no `/prefix`
+    /// is treated as a host route (`/32` for v4, `/128` for v6).
+    pub allowed_ips: Vec<IpCidr>,
+    pub allowed_protocols: Vec<String>,
+}
+
+/// A parsed CIDR range, stored as the network address plus prefix length so containment
+/// can be tested with bitwise masking instead of re-parsing on every `check_connection`.
+#[derive(Clone)]
+pub struct IpCidr {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpCidr {
+    fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.network, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix_len as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(*ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix_len as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(*ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl std::str::FromStr for IpCidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('/') {
+            Some((addr, prefix)) => {
+                let network: IpAddr = addr.parse().map_err(|_| format!("invalid IP in CIDR {s:?}"))?;
+                let prefix_len: u8 = prefix.parse().map_err(|_| format!("invalid prefix in CIDR {s:?}"))?;
+                let max_len = if network.is_ipv4() { 32 } else { 128 };
+                if prefix_len > max_len {
+                    return Err(format!("prefix /{prefix_len} exceeds /{max_len} in CIDR {s:?}"));
+                }
+                Ok(IpCidr { network, prefix_len })
+            }
+            None => {
+                let network: IpAddr = s.parse().map_err(|_| format!("invalid IP {s:?}"))?;
+                let prefix_len = if network.is_ipv4() { 32 } else { 128 };
+                Ok(IpCidr { network, prefix_len })
+            }
+        }
+    }
+}
+
+impl Serialize for IpCidr {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&format_args!("{}/{}", self.network, self.prefix_len))
+    }
+}
+
+impl<'de> Deserialize<'de> for IpCidr {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Matches an `allowed_domains` entry against the queried domain. A leading `*.` matches
+/// any subdomain, compared label-by-label so `evil-example.com` doesn't match
+/// `*.example.com`; anything else must match exactly.
+fn domain_matches(pattern: &str, domain: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(suffix) => {
+            domain.len() > suffix.len()
+                && domain.ends_with(suffix)
+                && domain.as_bytes()[domain.len() - suffix.len() - 1] == b'.'
+        }
+        None => pattern == domain,
+    }
+}
+
+/// A single rule-violation (or rule-pass) event buffered for upload to the collector.
+#[derive(Serialize, Deserialize)]
+pub struct LogRecord {
+    pub app_name: String,
+    pub domain: String,
+    pub ip: IpAddr,
+    pub protocol: String,
+    pub allowed: bool,
+}
+
+/// Key identifying a single `check_connection` evaluation for dedup purposes.
+type CheckKey = (String, String, IpAddr, String);
+type CheckFuture = Shared<BoxFuture<'static, bool>>;
+
+/// Messages accepted by the rule-store actor. The actor owns the `HashMap<String,
+/// FirewallRule>` and the log buffer outright, so mutation is serialized through this
+/// mailbox instead of a `std::sync::Mutex` held across an `.await`.
+enum Message {
+    CheckConnection {
+        app_name: String,
+        domain: String,
+        ip: IpAddr,
+        protocol: String,
+        reply: oneshot::Sender<bool>,
+    },
+    UpsertRule {
+        rule: FirewallRule,
+        reply: oneshot::Sender<()>,
+    },
+    RemoveRule {
+        app_name: String,
+        reply: oneshot::Sender<()>,
+    },
+    ReloadRules {
+        rules: HashMap<String, FirewallRule>,
+        reply: oneshot::Sender<()>,
+    },
+    CollectLogs {
+        reply: oneshot::Sender<()>,
+    },
+    DrainLogs {
+        reply: oneshot::Sender<Vec<LogRecord>>,
+    },
+    RequeueLogs(Vec<LogRecord>),
+    PushLog(LogRecord),
+}
+
+async fn run_rule_store(mut mailbox: mpsc::Receiver<Message>) {
+    let mut rules: HashMap<String, FirewallRule> = HashMap::new();
+    let mut logs: Vec<LogRecord> = Vec::new();
+
+    while let Some(message) = mailbox.recv().await {
+        match message {
+            Message::CheckConnection {
+                app_name,
+                domain,
+                ip,
+                protocol,
+                reply,
+            } => {
+                let allowed = rules
+                    .get(&app_name)
+                    .map(|rule| {
+                        rule.allowed_domains.iter().any(|pattern| domain_matches(pattern, &domain))
+                            && rule.allowed_ips.iter().any(|net| net.contains(&ip))
+                            && rule.allowed_protocols.contains(&protocol)
+                    })
+                    .unwrap_or(false);
+                let _ = reply.send(allowed);
+            }
+            Message::UpsertRule { rule, reply } => {
+                rules.insert(rule.app_name.clone(), rule);
+                let _ = reply.send(());
+            }
+            Message::RemoveRule { app_name, reply } => {
+                rules.remove(&app_name);
+                let _ = reply.send(());
+            }
+            Message::ReloadRules { rules: fresh, reply } => {
+                rules = fresh;
+                let _ = reply.send(());
+            }
+            Message::CollectLogs { reply } => {
+                // Implement log collection logic
+                let _ = reply.send(());
+            }
+            Message::DrainLogs { reply } => {
+                let _ = reply.send(std::mem::take(&mut logs));
+            }
+            Message::RequeueLogs(records) => {
+                logs.splice(0..0, records);
+            }
+            Message::PushLog(record) => {
+                logs.push(record);
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct FirewallAgent {
+    mailbox: mpsc::Sender<Message>,
+    /// In-flight `check_connection` evaluations, keyed by (app_name, domain, ip, protocol).
+    /// Entries are `Weak` so a cancelled leader can't strand its followers; a follower that
+    /// finds a dead weak ref simply becomes the new leader.
+    in_flight: Arc<Mutex<HashMap<CheckKey, Weak<CheckFuture>>>>,
+    pub(crate) tls: TlsConfig,
+    pub(crate) tls_connector: TlsConnector,
+    /// Separate connector for the HTTP/2 control channel: it negotiates ALPN `h2`
+    /// instead of the collector's `firewall-agent-logs/1`, so the two can't share one.
+    pub(crate) control_tls_connector: TlsConnector,
+}
+
+impl FirewallAgent {
+    pub fn new(tls_config: TlsConfig) -> io::Result<Self> {
+        let tls_connector = tls::build_connector(&tls_config)?;
+        let control_tls_connector = tls::build_control_connector(&tls_config)?;
+        let (mailbox, receiver) = mpsc::channel(256);
+        tokio::spawn(run_rule_store(receiver));
+        Ok(FirewallAgent {
+            mailbox,
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+            tls: tls_config,
+            tls_connector,
+            control_tls_connector,
+        })
+    }
+
+    async fn evaluate(mailbox: &mpsc::Sender<Message>, key: &CheckKey) -> bool {
+        let (app_name, domain, ip, protocol) = key.clone();
+        let (reply, reply_rx) = oneshot::channel();
+        let sent = mailbox
+            .send(Message::CheckConnection {
+                app_name,
+                domain,
+                ip,
+                protocol,
+                reply,
+            })
+            .await
+            .is_ok();
+        if !sent {
+            return false; // rule-store actor is gone: fail closed
+        }
+        reply_rx.await.unwrap_or(false)
+    }
+
+    pub async fn check_connection(&self, app_name: &str, domain: &str, ip: IpAddr, protocol: &str) -> bool {
+        let key: CheckKey = (app_name.to_string(), domain.to_string(), ip, protocol.to_string());
+
+        // Build a candidate leader future speculatively. Constructing a future doesn't
+        // poll it, so this is cheap and lets the upgrade-or-insert decision below happen
+        // under a single lock acquisition with no `.await` in between — otherwise two
+        // callers for the same key could both see "no live leader" and each become their
+        // own leader, which defeats the dedup this exists for.
+        let mailbox = self.mailbox.clone();
+        let in_flight_handle = Arc::clone(&self.in_flight);
+        let leader_key = key.clone();
+        let fut: BoxFuture<'static, bool> = async move {
+            let result = Self::evaluate(&mailbox, &leader_key).await;
+            // Drop our own entry so the next wave of callers re-checks fresh rules.
+            in_flight_handle.lock().unwrap().remove(&leader_key);
+            result
+        }
+        .boxed();
+        let candidate: Arc<CheckFuture> = Arc::new(fut.shared());
+
+        // Scoped so the `MutexGuard` (which isn't `Send`) never has to be carried across
+        // an `.await` point inside this `async fn`'s generated state machine.
+        let shared = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            match in_flight.get(&key).and_then(Weak::upgrade) {
+                Some(shared) => shared,
+                None => {
+                    in_flight.insert(key, Arc::downgrade(&candidate));
+                    candidate
+                }
+            }
+        };
+
+        shared.as_ref().clone().await
+    }
+
+    pub async fn upsert_rule(&self, rule: FirewallRule) {
+        let (reply, reply_rx) = oneshot::channel();
+        if self.mailbox.send(Message::UpsertRule { rule, reply }).await.is_ok() {
+            let _ = reply_rx.await;
+        }
+    }
+
+    pub async fn remove_rule(&self, app_name: &str) {
+        let (reply, reply_rx) = oneshot::channel();
+        let sent = self
+            .mailbox
+            .send(Message::RemoveRule {
+                app_name: app_name.to_string(),
+                reply,
+            })
+            .await
+            .is_ok();
+        if sent {
+            let _ = reply_rx.await;
+        }
+    }
+
+    /// Atomically swaps in a freshly parsed rule set, e.g. on a `SIGHUP` reload.
+    pub async fn reload_rules(&self, rules: HashMap<String, FirewallRule>) {
+        let (reply, reply_rx) = oneshot::channel();
+        if self.mailbox.send(Message::ReloadRules { rules, reply }).await.is_ok() {
+            let _ = reply_rx.await;
+        }
+    }
+
+    pub async fn collect_logs(&self) {
+        let (reply, reply_rx) = oneshot::channel();
+        if self.mailbox.send(Message::CollectLogs { reply }).await.is_ok() {
+            let _ = reply_rx.await;
+        }
+    }
+
+    pub(crate) async fn drain_logs(&self) -> Vec<LogRecord> {
+        let (reply, reply_rx) = oneshot::channel();
+        if self.mailbox.send(Message::DrainLogs { reply }).await.is_err() {
+            return Vec::new();
+        }
+        reply_rx.await.unwrap_or_default()
+    }
+
+    pub(crate) async fn requeue_logs(&self, records: Vec<LogRecord>) {
+        let _ = self.mailbox.send(Message::RequeueLogs(records)).await;
+    }
+
+    /// Appends a single log record (e.g. a proxy denial) straight to the buffer.
+    pub async fn record(&self, record: LogRecord) {
+        let _ = self.mailbox.send(Message::PushLog(record)).await;
+    }
+
+    /// Uploads buffered log records to the collector over a mutually-authenticated TLS
+    /// connection, retrying transient I/O errors before giving the records back to the
+    /// buffer so the next tick picks them up again.
+    pub async fn send_logs_to_server(&self) {
+        let records = self.drain_logs().await;
+        if records.is_empty() {
+            return;
+        }
+
+        const MAX_ATTEMPTS: u32 = 3;
+        for attempt in 1..=MAX_ATTEMPTS {
+            match self.try_send_logs(&records).await {
+                Ok(()) => return,
+                Err(err) if attempt < MAX_ATTEMPTS => {
+                    eprintln!("send_logs_to_server: attempt {attempt} failed ({err}), retrying");
+                    time::sleep(Duration::from_secs(attempt as u64)).await;
+                }
+                Err(err) => {
+                    eprintln!("send_logs_to_server: giving up after {attempt} attempts: {err}");
+                    self.requeue_logs(records).await;
+                    return;
+                }
+            }
+        }
+    }
+
+    async fn try_send_logs(&self, records: &[LogRecord]) -> io::Result<()> {
+        let tcp = TcpStream::connect(self.tls.collector_addr).await?;
+        let server_name = ServerName::try_from(self.tls.server_name.as_str())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid server_name in TlsConfig"))?;
+        let mut stream = self.tls_connector.connect(server_name, tcp).await?;
+
+        for record in records {
+            let payload = serde_json::to_vec(record)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            stream.write_all(&(payload.len() as u32).to_be_bytes()).await?;
+            stream.write_all(&payload).await?;
+        }
+        stream.flush().await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,15 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::agent::FirewallRule;
+
+/// Loads the full rule set from a JSON config file, keyed by `app_name`. Used both at
+/// startup and on a `SIGHUP` reload.
+pub fn load_rules(path: &Path) -> io::Result<HashMap<String, FirewallRule>> {
+    let contents = fs::read_to_string(path)?;
+    let rules: Vec<FirewallRule> =
+        serde_json::from_str(&contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(rules.into_iter().map(|rule| (rule.app_name.clone(), rule)).collect())
+}
@@ -1,50 +1,26 @@
-use std::collections::HashMap;
-use std::net::IpAddr;
-use std::sync::{Arc, Mutex};
-use tokio::net::TcpStream;
-use tokio::time::{self, Duration};
+mod agent;
+mod config;
+mod control;
+mod proxy;
+mod signals;
+mod tls;
 
-struct FirewallRule {
-    app_name: String,
-    allowed_domains: Vec<String>,
-    allowed_ips: Vec<IpAddr>,
-    allowed_protocols: Vec<String>,
-}
-
-struct FirewallAgent {
-    rules: Arc<Mutex<HashMap<String, FirewallRule>>>,
-}
-
-impl FirewallAgent {
-    fn new() -> Self {
-        FirewallAgent {
-            rules: Arc::new(Mutex::new(HashMap::new())),
-        }
-    }
-
-    async fn check_connection(&self, app_name: &str, domain: &str, ip: IpAddr, protocol: &str) -> bool {
-        let rules = self.rules.lock().unwrap();
-        if let Some(rule) = rules.get(app_name) {
-            rule.allowed_domains.contains(&domain.to_string())
-                && rule.allowed_ips.contains(&ip)
-                && rule.allowed_protocols.contains(&protocol.to_string())
-        } else {
-            false
-        }
-    }
-
-    async fn collect_logs(&self) {
-        // Implement log collection logic
-    }
-
-    async fn send_logs_to_server(&self) {
-        // Implement log sending logic
-    }
-}
+use agent::FirewallAgent;
+use proxy::{ProxyConfig, ProxyServer};
+use tls::TlsConfig;
 
 #[tokio::main]
 async fn main() {
-    let agent = FirewallAgent::new();
+    let tls_config = TlsConfig {
+        ca: "certs/ca.pem".into(),
+        client_cert: "certs/agent-cert.pem".into(),
+        client_key: "certs/agent-key.pem".into(),
+        server_name: "collector.firewall.internal".to_string(),
+        collector_addr: "127.0.0.1:8443".parse().unwrap(),
+        control_addr: "127.0.0.1:8444".parse().unwrap(),
+        control_server_name: "control.firewall.internal".to_string(),
+    };
+    let agent = FirewallAgent::new(tls_config).expect("failed to initialize TLS transport");
 
     // Example usage
     let app_name = "example_app";
@@ -55,16 +31,31 @@ async fn main() {
     let allowed = agent.check_connection(app_name, domain, ip, protocol).await;
     println!("Connection allowed: {}", allowed);
 
-    // Start log collection and sending tasks
+    // Stream rule updates in over a long-lived HTTP/2 control channel instead of
+    // polling the server every 60 seconds; buffered logs are uploaded to the collector
+    // over the mTLS connection every 250ms. Logs are not sent on the control channel.
+    tokio::spawn(control::run(agent.clone()));
+
+    // Enforce rule decisions on traffic redirected to us (e.g. via an iptables
+    // `REDIRECT` rule) instead of only reporting on connections after the fact.
+    let proxy = ProxyServer::new(
+        agent.clone(),
+        ProxyConfig {
+            bind_addr: "127.0.0.1:8080".parse().unwrap(),
+            connect_timeout: std::time::Duration::from_secs(10),
+            idle_timeout: std::time::Duration::from_secs(120),
+        },
+    );
     tokio::spawn(async move {
-        let mut interval = time::interval(Duration::from_secs(60));
-        loop {
-            interval.tick().await;
-            agent.collect_logs().await;
-            agent.send_logs_to_server().await;
+        if let Err(err) = proxy.run().await {
+            eprintln!("proxy server stopped: {err}");
         }
     });
 
-    // Keep the main thread running
-    tokio::signal::ctrl_c().await.unwrap();
-}
\ No newline at end of file
+    // Drives log collection and reacts to SIGHUP/SIGINT/SIGTERM (Ctrl-C on Windows),
+    // returning once a shutdown signal arrives.
+    signals::run(agent.clone(), "config/rules.json".into()).await;
+
+    // Flush whatever's left in the log buffer before exiting.
+    agent.send_logs_to_server().await;
+}
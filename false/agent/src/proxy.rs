@@ -0,0 +1,225 @@
+use std::io;
+use std::net::SocketAddr;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{self, Duration};
+
+use crate::agent::{FirewallAgent, LogRecord};
+
+/// Bind address and per-connection timeouts for the enforcing TCP proxy.
+pub struct ProxyConfig {
+    pub bind_addr: SocketAddr,
+    pub connect_timeout: Duration,
+    /// Per-direction inactivity deadline for a spliced session: reset on every read and
+    /// write, so an allowed connection that stalls (peer accepts but never sends/receives)
+    /// gets dropped, but a busy, long-lived transfer is never killed just for running long.
+    pub idle_timeout: Duration,
+}
+
+/// Inline gatekeeper: accepts outbound connections redirected to it (e.g. via an
+/// iptables `REDIRECT` rule), asks the agent whether the owning app/destination/protocol
+/// is allowed, and either splices the connection through to the real destination or
+/// resets it and records a denial. This is what turns `check_connection` from a passive
+/// checker `main` merely printed into something that actually blocks traffic.
+pub struct ProxyServer {
+    agent: FirewallAgent,
+    config: ProxyConfig,
+}
+
+impl ProxyServer {
+    pub fn new(agent: FirewallAgent, config: ProxyConfig) -> Self {
+        ProxyServer { agent, config }
+    }
+
+    pub async fn run(&self) -> io::Result<()> {
+        let listener = TcpListener::bind(self.config.bind_addr).await?;
+        loop {
+            let (inbound, peer_addr) = listener.accept().await?;
+            let agent = self.agent.clone();
+            let connect_timeout = self.config.connect_timeout;
+            let idle_timeout = self.config.idle_timeout;
+            tokio::spawn(async move {
+                if let Err(err) =
+                    handle_connection(&agent, inbound, peer_addr, connect_timeout, idle_timeout).await
+                {
+                    eprintln!("proxy: connection from {peer_addr} failed: {err}");
+                }
+            });
+        }
+    }
+}
+
+async fn handle_connection(
+    agent: &FirewallAgent,
+    inbound: TcpStream,
+    peer_addr: SocketAddr,
+    connect_timeout: Duration,
+    idle_timeout: Duration,
+) -> io::Result<()> {
+    let original_dst = original_destination(&inbound)?;
+    let app_name = owning_app_name(peer_addr)?;
+    // Without SNI inspection we don't have the original hostname for plain TCP, so we
+    // fall back to matching on the IP literal; allowed_domains entries for this traffic
+    // need to be the IP itself until domain resolution lands here.
+    let domain = original_dst.ip().to_string();
+    let protocol = "tcp";
+
+    let allowed = agent
+        .check_connection(&app_name, &domain, original_dst.ip(), protocol)
+        .await;
+
+    if !allowed {
+        agent
+            .record(LogRecord {
+                app_name,
+                domain,
+                ip: original_dst.ip(),
+                protocol: protocol.to_string(),
+                allowed: false,
+            })
+            .await;
+        // Reset rather than a clean close, so the blocked app sees a hard failure. Tokio's
+        // own `set_linger` is deprecated because `SO_LINGER` blocks the thread on drop,
+        // which is fine here since we're about to drop the socket anyway.
+        socket2::SockRef::from(&inbound).set_linger(Some(Duration::from_secs(0)))?;
+        drop(inbound);
+        return Ok(());
+    }
+
+    let outbound = time::timeout(connect_timeout, TcpStream::connect(original_dst))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "connect to destination timed out"))??;
+
+    splice(inbound, outbound, idle_timeout).await
+}
+
+async fn splice(inbound: TcpStream, outbound: TcpStream, idle_timeout: Duration) -> io::Result<()> {
+    let (mut inbound_rd, mut inbound_wr) = inbound.into_split();
+    let (mut outbound_rd, mut outbound_wr) = outbound.into_split();
+
+    // Each direction pumps independently and shuts down its destination's write half on
+    // EOF, so a half-close (client done sending, or server done responding) propagates
+    // instead of leaving the other direction's write half open forever.
+    tokio::try_join!(
+        pump(&mut inbound_rd, &mut outbound_wr, idle_timeout),
+        pump(&mut outbound_rd, &mut inbound_wr, idle_timeout),
+    )?;
+    Ok(())
+}
+
+/// Copies from `src` to `dst` until EOF, then shuts `dst` down. Every read and write is
+/// bounded by `idle_timeout`, reset on each one — so a peer that goes quiet gets dropped,
+/// but a connection that's continuously transferring is never killed just for running long.
+async fn pump<R, W>(src: &mut R, dst: &mut W, idle_timeout: Duration) -> io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut buf = [0u8; 8192];
+    loop {
+        let n = time::timeout(idle_timeout, src.read(&mut buf))
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "connection idle for too long"))??;
+        if n == 0 {
+            break;
+        }
+        time::timeout(idle_timeout, dst.write_all(&buf[..n]))
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "connection idle for too long"))??;
+    }
+    dst.shutdown().await
+}
+
+#[cfg(target_os = "linux")]
+fn original_destination(stream: &TcpStream) -> io::Result<SocketAddr> {
+    use std::mem;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::os::unix::io::AsRawFd;
+
+    const SO_ORIGINAL_DST: libc::c_int = 80;
+
+    let fd = stream.as_raw_fd();
+    let mut addr: libc::sockaddr_in = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<libc::sockaddr_in>() as libc::socklen_t;
+
+    let ret = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_IP,
+            SO_ORIGINAL_DST,
+            &mut addr as *mut _ as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let ip = Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr));
+    let port = u16::from_be(addr.sin_port);
+    Ok(SocketAddr::new(IpAddr::V4(ip), port))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn original_destination(_stream: &TcpStream) -> io::Result<SocketAddr> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "transparent redirect interception (SO_ORIGINAL_DST) is only implemented for Linux",
+    ))
+}
+
+#[cfg(target_os = "linux")]
+fn owning_app_name(peer_addr: SocketAddr) -> io::Result<String> {
+    let inode = find_socket_inode(peer_addr)?;
+    find_process_name_for_inode(inode)
+}
+
+#[cfg(target_os = "linux")]
+fn find_socket_inode(peer_addr: SocketAddr) -> io::Result<u64> {
+    let contents = std::fs::read_to_string("/proc/net/tcp")?;
+    let port_hex = format!("{:04X}", peer_addr.port());
+    for line in contents.lines().skip(1) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let Some(local_address) = fields.get(1) else { continue };
+        let Some((_, port)) = local_address.split_once(':') else { continue };
+        if port.eq_ignore_ascii_case(&port_hex) {
+            return fields
+                .get(9)
+                .and_then(|inode| inode.parse().ok())
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed inode in /proc/net/tcp"));
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::NotFound, "no /proc/net/tcp entry for this connection"))
+}
+
+#[cfg(target_os = "linux")]
+fn find_process_name_for_inode(inode: u64) -> io::Result<String> {
+    let target = format!("socket:[{inode}]");
+    for entry in std::fs::read_dir("/proc")?.flatten() {
+        let Some(pid) = entry.file_name().to_str().and_then(|s| s.parse::<u32>().ok()) else {
+            continue;
+        };
+        let Ok(fds) = std::fs::read_dir(entry.path().join("fd")) else {
+            continue;
+        };
+        for fd in fds.flatten() {
+            if std::fs::read_link(fd.path()).map(|l| l.to_string_lossy() == target.as_str()).unwrap_or(false) {
+                let exe = std::fs::read_link(format!("/proc/{pid}/exe"))?;
+                return Ok(exe
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| format!("pid:{pid}")));
+            }
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::NotFound, "no process owns this connection's socket"))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn owning_app_name(_peer_addr: SocketAddr) -> io::Result<String> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "owning-app lookup (/proc/net/tcp) is only implemented for Linux",
+    ))
+}
@@ -0,0 +1,69 @@
+use std::path::PathBuf;
+
+use tokio::time::{self, Duration};
+
+use crate::agent::FirewallAgent;
+use crate::config;
+
+/// Drives the agent's background lifecycle: reloads rules from `rules_config_path` on
+/// `SIGHUP` (Unix) without dropping connections, collects logs on a periodic timer, and
+/// returns as soon as a shutdown signal (`SIGINT`/`SIGTERM` on Unix, Ctrl-C on Windows)
+/// arrives so the caller can flush buffered logs before exiting.
+pub async fn run(agent: FirewallAgent, rules_config_path: PathBuf) {
+    let mut collect_interval = time::interval(Duration::from_secs(60));
+
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut sigint = signal(SignalKind::interrupt()).expect("failed to register SIGINT handler");
+        let mut sigterm = signal(SignalKind::terminate()).expect("failed to register SIGTERM handler");
+        let mut sighup = signal(SignalKind::hangup()).expect("failed to register SIGHUP handler");
+
+        loop {
+            tokio::select! {
+                _ = sigint.recv() => {
+                    println!("received SIGINT, shutting down");
+                    return;
+                }
+                _ = sigterm.recv() => {
+                    println!("received SIGTERM, shutting down");
+                    return;
+                }
+                _ = sighup.recv() => {
+                    println!("received SIGHUP, reloading rules from {}", rules_config_path.display());
+                    reload_rules(&agent, &rules_config_path).await;
+                }
+                _ = collect_interval.tick() => {
+                    agent.collect_logs().await;
+                }
+            }
+        }
+    }
+
+    #[cfg(windows)]
+    {
+        let mut ctrl_c = tokio::signal::windows::ctrl_c().expect("failed to register Ctrl-C handler");
+        loop {
+            tokio::select! {
+                _ = ctrl_c.recv() => {
+                    println!("received Ctrl-C, shutting down");
+                    return;
+                }
+                _ = collect_interval.tick() => {
+                    agent.collect_logs().await;
+                }
+            }
+        }
+    }
+}
+
+async fn reload_rules(agent: &FirewallAgent, path: &std::path::Path) {
+    match config::load_rules(path) {
+        // The actor processes one message at a time, so this swap is atomic with respect
+        // to `check_connection` calls: no request sees a half-applied rule set, and no
+        // connection is dropped to make it happen.
+        Ok(rules) => agent.reload_rules(rules).await,
+        Err(err) => eprintln!("rule reload failed, keeping existing rules: {err}"),
+    }
+}
@@ -0,0 +1,85 @@
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use tokio_rustls::rustls::{self, Certificate, PrivateKey, RootCertStore};
+use tokio_rustls::TlsConnector;
+
+/// Mutual-TLS settings for the agent's connection to the log collector.
+///
+/// `ca` pins the collector's issuing CA so a rogue collector can't receive logs, and
+/// `client_cert`/`client_key` let the collector authenticate the agent in turn. The same
+/// cert/key pair also authenticates the agent on the HTTP/2 control channel.
+#[derive(Clone)]
+pub struct TlsConfig {
+    pub ca: PathBuf,
+    pub client_cert: PathBuf,
+    pub client_key: PathBuf,
+    pub server_name: String,
+    pub collector_addr: SocketAddr,
+    /// Address of the HTTP/2 control-plane server (rule sync only; logs go to
+    /// `collector_addr`).
+    pub control_addr: SocketAddr,
+    /// Hostname to verify the control-plane server's certificate against. Kept separate
+    /// from `server_name` since `control_addr` is a different server than the collector.
+    pub control_server_name: String,
+}
+
+/// Builds a `TlsConnector` configured for mutual auth and ALPN-negotiated to the
+/// collector's log-ingest protocol.
+pub fn build_connector(config: &TlsConfig) -> io::Result<TlsConnector> {
+    build_connector_with_alpn(config, b"firewall-agent-logs/1")
+}
+
+/// Builds a `TlsConnector` for the HTTP/2 control channel. RFC 7540 requires ALPN `h2`
+/// for HTTP/2-over-TLS; reusing the collector's `firewall-agent-logs/1` protocol here
+/// leaves no ALPN overlap with the control server and rustls aborts the handshake with
+/// `NoApplicationProtocol`.
+pub fn build_control_connector(config: &TlsConfig) -> io::Result<TlsConnector> {
+    build_connector_with_alpn(config, b"h2")
+}
+
+fn build_connector_with_alpn(config: &TlsConfig, alpn_protocol: &[u8]) -> io::Result<TlsConnector> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(&config.ca)? {
+        roots
+            .add(&cert)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    }
+
+    let client_certs = load_certs(&config.client_cert)?;
+    let client_key = load_private_key(&config.client_key)?;
+
+    let mut client_config = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_client_auth_cert(client_certs, client_key)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    client_config.alpn_protocols = vec![alpn_protocol.to_vec()];
+
+    Ok(TlsConnector::from(Arc::new(client_config)))
+}
+
+fn load_certs(path: &PathBuf) -> io::Result<Vec<Certificate>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let certs = rustls_pemfile::certs(&mut reader)?;
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+/// Parses `path` as a PKCS#8, PKCS#1 (`RSA PRIVATE KEY`), or SEC1 (`EC PRIVATE KEY`)
+/// client key, trying each format in turn since PEM files don't self-describe which one
+/// they contain.
+fn load_private_key(path: &PathBuf) -> io::Result<PrivateKey> {
+    if let Some(key) = rustls_pemfile::pkcs8_private_keys(&mut BufReader::new(File::open(path)?))?.pop() {
+        return Ok(PrivateKey(key));
+    }
+    if let Some(key) = rustls_pemfile::rsa_private_keys(&mut BufReader::new(File::open(path)?))?.pop() {
+        return Ok(PrivateKey(key));
+    }
+    if let Some(key) = rustls_pemfile::ec_private_keys(&mut BufReader::new(File::open(path)?))?.pop() {
+        return Ok(PrivateKey(key));
+    }
+    Err(io::Error::new(io::ErrorKind::InvalidData, "no private key found in client_key file"))
+}